@@ -0,0 +1,33 @@
+use crate::theme::{ThemePatch, ThemeVariants};
+use serde::Deserialize;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Colors {
+    pub background_color: String,
+    pub primary_color: String,
+    pub secondary_color: String,
+    pub tertiary_color: String,
+    pub accent_color: String,
+    pub text_primary: String,
+    pub text_secondary: String,
+    pub shadow_color: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Visual {
+    pub colors: Colors,
+    /// A practice-supplied light/dark palette pair. Takes precedence over
+    /// `colors` when present.
+    #[serde(default)]
+    pub theme: Option<ThemeVariants>,
+    /// A sparse override layered over `Theme::default()`. Checked after
+    /// `theme` and before falling back to `colors`.
+    #[serde(default)]
+    pub theme_patch: Option<ThemePatch>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Practice {
+    pub id: String,
+    pub visual: Visual,
+}
@@ -1,9 +1,21 @@
 use crate::models::practice::Colors;
 use crate::data::practice_loader::get_practice_by_id;
 use once_cell::sync::Lazy;
+use std::collections::{HashMap, HashSet};
 use std::sync::Mutex;
 use serde::Deserialize;
 
+const FIELD_NAMES: [&str; 8] = [
+    "background_color",
+    "primary_color",
+    "secondary_color",
+    "tertiary_color",
+    "accent_color",
+    "text_primary",
+    "text_secondary",
+    "shadow_color",
+];
+
 #[derive(Clone, Debug, Deserialize)]
 pub struct Theme {
     pub background_color: String,
@@ -31,27 +43,655 @@ impl Default for Theme {
     }
 }
 
+impl From<Colors> for Theme {
+    fn from(colors: Colors) -> Self {
+        Theme {
+            background_color: colors.background_color,
+            primary_color: colors.primary_color,
+            secondary_color: colors.secondary_color,
+            tertiary_color: colors.tertiary_color,
+            accent_color: colors.accent_color,
+            text_primary: colors.text_primary,
+            text_secondary: colors.text_secondary,
+            shadow_color: colors.shadow_color,
+        }
+    }
+}
+
+/// A sparse set of theme overrides. Every field is optional, so a practice
+/// can tweak e.g. just `accent_color` and `background_color` without
+/// redefining all eight fields of a full [`Theme`].
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct ThemePatch {
+    pub background_color: Option<String>,
+    pub primary_color: Option<String>,
+    pub secondary_color: Option<String>,
+    pub tertiary_color: Option<String>,
+    pub accent_color: Option<String>,
+    pub text_primary: Option<String>,
+    pub text_secondary: Option<String>,
+    pub shadow_color: Option<String>,
+}
+
+impl Theme {
+    /// Overwrites only the fields that are `Some` in `patch`, leaving
+    /// everything else untouched. Used to layer a partial practice override
+    /// on top of `Theme::default()` so a malformed or incomplete practice
+    /// degrades field-by-field instead of losing the whole palette.
+    pub fn apply_patch(&mut self, patch: &ThemePatch) {
+        if let Some(v) = &patch.background_color {
+            self.background_color = v.clone();
+        }
+        if let Some(v) = &patch.primary_color {
+            self.primary_color = v.clone();
+        }
+        if let Some(v) = &patch.secondary_color {
+            self.secondary_color = v.clone();
+        }
+        if let Some(v) = &patch.tertiary_color {
+            self.tertiary_color = v.clone();
+        }
+        if let Some(v) = &patch.accent_color {
+            self.accent_color = v.clone();
+        }
+        if let Some(v) = &patch.text_primary {
+            self.text_primary = v.clone();
+        }
+        if let Some(v) = &patch.text_secondary {
+            self.text_secondary = v.clone();
+        }
+        if let Some(v) = &patch.shadow_color {
+            self.shadow_color = v.clone();
+        }
+    }
+}
+
+/// Appearance preference for a theme: either pinned to `Light`/`Dark`, or
+/// following the OS-level `prefers-color-scheme` setting.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    System,
+    Light,
+    Dark,
+}
+
+impl Default for Mode {
+    fn default() -> Self {
+        Mode::System
+    }
+}
+
+/// A practice's light and dark palettes, plus the mode it wants to resolve
+/// with by default. Both variants are kept around (not just the resolved
+/// one) so flipping between them is instant and doesn't require re-reading
+/// the practice.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ThemeVariants {
+    #[serde(default)]
+    pub mode: Mode,
+    pub light: Theme,
+    pub dark: Theme,
+}
+
+impl ThemeVariants {
+    fn single(theme: Theme) -> Self {
+        ThemeVariants {
+            mode: Mode::Dark,
+            light: theme.clone(),
+            dark: theme,
+        }
+    }
+
+    fn resolve(&self, mode: Mode) -> &Theme {
+        match mode {
+            Mode::Light => &self.light,
+            Mode::Dark => &self.dark,
+            Mode::System => {
+                if system_prefers_dark() {
+                    &self.dark
+                } else {
+                    &self.light
+                }
+            }
+        }
+    }
+}
+
+/// A theme that failed to resolve or load: a token reference formed a
+/// cycle, a token reference pointed at an unknown field, or a user theme
+/// file on disk was missing or malformed.
+#[derive(Debug)]
+pub enum ThemeError {
+    CyclicReference(String),
+    UnknownReference(String),
+    Io(String),
+    Parse(String),
+}
+
+enum ColorExpr {
+    Literal(String),
+    Reference(String),
+    Lighten(Box<ColorExpr>, f32),
+    Darken(Box<ColorExpr>, f32),
+    Alpha(Box<ColorExpr>, f32),
+}
+
+/// Splits a function's argument list on its top-level comma, i.e. the one
+/// not nested inside a parenthesized sub-expression. This is what lets a
+/// derivation nest, e.g. `alpha(lighten($accent_color, 10%), 0.3)` splits
+/// into `lighten($accent_color, 10%)` and `0.3`, not `lighten($accent_color`
+/// and `10%), 0.3`. Note `lighten`/`darken` only operate on hex colors, so
+/// `alpha(...)` (which produces an `rgba(...)` string) can only appear as
+/// the outermost call, never nested inside `lighten`/`darken`.
+fn split_fn_args(inner: &str) -> (&str, &str) {
+    let mut depth = 0i32;
+    for (i, c) in inner.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                return (inner[..i].trim(), inner[i + 1..].trim());
+            }
+            _ => {}
+        }
+    }
+    (inner.trim(), "0")
+}
+
+fn parse_percent(s: &str) -> f32 {
+    s.trim_end_matches('%').trim().parse().unwrap_or(0.0)
+}
+
+fn parse_color_expr(raw: &str) -> ColorExpr {
+    let s = raw.trim();
+    if let Some(inner) = s.strip_prefix("lighten(").and_then(|r| r.strip_suffix(')')) {
+        let (arg, amount) = split_fn_args(inner);
+        return ColorExpr::Lighten(Box::new(parse_color_expr(arg)), parse_percent(amount));
+    }
+    if let Some(inner) = s.strip_prefix("darken(").and_then(|r| r.strip_suffix(')')) {
+        let (arg, amount) = split_fn_args(inner);
+        return ColorExpr::Darken(Box::new(parse_color_expr(arg)), parse_percent(amount));
+    }
+    if let Some(inner) = s.strip_prefix("alpha(").and_then(|r| r.strip_suffix(')')) {
+        let (arg, amount) = split_fn_args(inner);
+        return ColorExpr::Alpha(Box::new(parse_color_expr(arg)), amount.parse().unwrap_or(1.0));
+    }
+    if let Some(name) = s.strip_prefix('$') {
+        return ColorExpr::Reference(name.to_string());
+    }
+    ColorExpr::Literal(s.to_string())
+}
+
+/// Whether `s` is a `#RRGGBB` hex color. `lighten`/`darken` only make sense
+/// on such a value, so this guards against silently mis-deriving one, e.g.
+/// from a nested `alpha(...)` result. `alpha` also reads its input as hex
+/// and does not parse an `rgba(...)` string.
+fn is_hex_color(s: &str) -> bool {
+    s.len() == 7 && s.starts_with('#') && s[1..].chars().all(|c| c.is_ascii_hexdigit())
+}
+
+fn hex_to_rgb(hex: &str) -> (u8, u8, u8) {
+    let hex = hex.trim_start_matches('#');
+    let r = u8::from_str_radix(hex.get(0..2).unwrap_or("00"), 16).unwrap_or(0);
+    let g = u8::from_str_radix(hex.get(2..4).unwrap_or("00"), 16).unwrap_or(0);
+    let b = u8::from_str_radix(hex.get(4..6).unwrap_or("00"), 16).unwrap_or(0);
+    (r, g, b)
+}
+
+fn rgb_to_hex(r: u8, g: u8, b: u8) -> String {
+    format!("#{:02X}{:02X}{:02X}", r, g, b)
+}
+
+fn rgb_to_hsl(r: u8, g: u8, b: u8) -> (f32, f32, f32) {
+    let r = r as f32 / 255.0;
+    let g = g as f32 / 255.0;
+    let b = b as f32 / 255.0;
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let l = (max + min) / 2.0;
+    if (max - min).abs() < f32::EPSILON {
+        return (0.0, 0.0, l);
+    }
+    let d = max - min;
+    let s = if l > 0.5 { d / (2.0 - max - min) } else { d / (max + min) };
+    let h = if max == r {
+        (g - b) / d + if g < b { 6.0 } else { 0.0 }
+    } else if max == g {
+        (b - r) / d + 2.0
+    } else {
+        (r - g) / d + 4.0
+    } * 60.0;
+    (h, s, l)
+}
+
+fn hue_to_rgb(p: f32, q: f32, t: f32) -> f32 {
+    let mut t = t;
+    if t < 0.0 {
+        t += 1.0;
+    }
+    if t > 1.0 {
+        t -= 1.0;
+    }
+    if t < 1.0 / 6.0 {
+        return p + (q - p) * 6.0 * t;
+    }
+    if t < 1.0 / 2.0 {
+        return q;
+    }
+    if t < 2.0 / 3.0 {
+        return p + (q - p) * (2.0 / 3.0 - t) * 6.0;
+    }
+    p
+}
+
+fn hsl_to_rgb(h: f32, s: f32, l: f32) -> (u8, u8, u8) {
+    if s == 0.0 {
+        let v = (l * 255.0).round() as u8;
+        return (v, v, v);
+    }
+    let q = if l < 0.5 { l * (1.0 + s) } else { l + s - l * s };
+    let p = 2.0 * l - q;
+    let h = h / 360.0;
+    let r = hue_to_rgb(p, q, h + 1.0 / 3.0);
+    let g = hue_to_rgb(p, q, h);
+    let b = hue_to_rgb(p, q, h - 1.0 / 3.0);
+    (
+        (r * 255.0).round() as u8,
+        (g * 255.0).round() as u8,
+        (b * 255.0).round() as u8,
+    )
+}
+
+/// Lightens a `#RRGGBB` color by `percent` lightness. Non-hex input (e.g. an
+/// `rgba(...)` string from a nested `alpha(...)` call) is returned
+/// unchanged rather than silently misparsed into an unrelated gray.
+fn lighten(hex: &str, percent: f32) -> String {
+    if !is_hex_color(hex) {
+        return hex.to_string();
+    }
+    let (r, g, b) = hex_to_rgb(hex);
+    let (h, s, l) = rgb_to_hsl(r, g, b);
+    let (r, g, b) = hsl_to_rgb(h, s, (l + percent / 100.0).clamp(0.0, 1.0));
+    rgb_to_hex(r, g, b)
+}
+
+/// Darkens a `#RRGGBB` color by `percent` lightness. See `lighten` for why
+/// non-hex input is passed through unchanged instead of misparsed.
+fn darken(hex: &str, percent: f32) -> String {
+    if !is_hex_color(hex) {
+        return hex.to_string();
+    }
+    let (r, g, b) = hex_to_rgb(hex);
+    let (h, s, l) = rgb_to_hsl(r, g, b);
+    let (r, g, b) = hsl_to_rgb(h, s, (l - percent / 100.0).clamp(0.0, 1.0));
+    rgb_to_hex(r, g, b)
+}
+
+fn with_alpha(hex: &str, alpha: f32) -> String {
+    let (r, g, b) = hex_to_rgb(hex);
+    format!("rgba({}, {}, {}, {})", r, g, b, alpha)
+}
+
+const READABLE_LIGHT: &str = "#FFFFFF";
+const READABLE_DARK: &str = "#0A0C11";
+const WCAG_AA_CONTRAST: f32 = 4.5;
+
+fn linearize_channel(c: f32) -> f32 {
+    if c <= 0.03928 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// WCAG relative luminance of a hex color, per the 2.x spec.
+fn relative_luminance(hex: &str) -> f32 {
+    let (r, g, b) = hex_to_rgb(hex);
+    let r = linearize_channel(r as f32 / 255.0);
+    let g = linearize_channel(g as f32 / 255.0);
+    let b = linearize_channel(b as f32 / 255.0);
+    0.2126 * r + 0.7152 * g + 0.0722 * b
+}
+
+/// WCAG contrast ratio between two relative luminances, always >= 1.0.
+fn contrast_ratio(l1: f32, l2: f32) -> f32 {
+    let (hi, lo) = if l1 >= l2 { (l1, l2) } else { (l2, l1) };
+    (hi + 0.05) / (lo + 0.05)
+}
+
+const MUTED_SECONDARY_SHIFT: f32 = 15.0;
+
+/// Picks white vs. near-black for text on `background`, whichever clears the
+/// higher contrast ratio, and warns if even the better choice still falls
+/// short of the WCAG AA threshold (4.5:1) so theme authors can fix it.
+/// Returns `(text_primary, text_secondary)`, where `text_secondary` is a
+/// muted variant of the *chosen* primary (shifted toward the background)
+/// rather than the rejected extreme, so it stays readable on the same
+/// `background` instead of only on an inverse surface.
+fn contrast_aware_text_colors(background: &str) -> (String, String) {
+    let bg_luminance = relative_luminance(background);
+    let white_ratio = contrast_ratio(bg_luminance, relative_luminance(READABLE_LIGHT));
+    let black_ratio = contrast_ratio(bg_luminance, relative_luminance(READABLE_DARK));
+
+    let (primary, primary_ratio) = if white_ratio >= black_ratio {
+        (READABLE_LIGHT, white_ratio)
+    } else {
+        (READABLE_DARK, black_ratio)
+    };
+
+    if primary_ratio < WCAG_AA_CONTRAST {
+        warn_low_contrast(background, primary, primary_ratio);
+    }
+
+    let secondary = if primary == READABLE_LIGHT {
+        darken(primary, MUTED_SECONDARY_SHIFT)
+    } else {
+        lighten(primary, MUTED_SECONDARY_SHIFT)
+    };
+
+    (primary.to_string(), secondary)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn warn_low_contrast(background: &str, text: &str, ratio: f32) {
+    println!(
+        "Warning: {} on {} only reaches {:.2}:1 contrast, below the WCAG AA 4.5:1 threshold",
+        text, background, ratio
+    );
+}
+
+#[cfg(target_arch = "wasm32")]
+fn warn_low_contrast(background: &str, text: &str, ratio: f32) {
+    web_sys::console::warn_1(
+        &format!(
+            "Warning: {} on {} only reaches {:.2}:1 contrast, below the WCAG AA 4.5:1 threshold",
+            text, background, ratio
+        )
+        .into(),
+    );
+}
+
+/// Resolves a set of (possibly token/derivation-valued) color fields into a
+/// concrete `Theme`, topologically following `$field_name` references and
+/// evaluating `lighten`/`darken`/`alpha` functions. Fields not present in
+/// `raw` fall back to `Theme::default()`'s corresponding literal.
+struct TokenResolver {
+    raw: HashMap<&'static str, String>,
+    resolved: HashMap<&'static str, String>,
+    visiting: HashSet<&'static str>,
+}
+
+impl TokenResolver {
+    fn new(raw: HashMap<&'static str, String>) -> Self {
+        TokenResolver {
+            raw,
+            resolved: HashMap::new(),
+            visiting: HashSet::new(),
+        }
+    }
+
+    fn default_value(name: &str) -> String {
+        let default = Theme::default();
+        match name {
+            "background_color" => default.background_color,
+            "primary_color" => default.primary_color,
+            "secondary_color" => default.secondary_color,
+            "tertiary_color" => default.tertiary_color,
+            "accent_color" => default.accent_color,
+            "text_primary" => default.text_primary,
+            "text_secondary" => default.text_secondary,
+            "shadow_color" => default.shadow_color,
+            _ => default.background_color,
+        }
+    }
+
+    fn resolve(&mut self, name: &'static str) -> Result<String, ThemeError> {
+        if let Some(value) = self.resolved.get(name) {
+            return Ok(value.clone());
+        }
+        if !self.visiting.insert(name) {
+            return Err(ThemeError::CyclicReference(name.to_string()));
+        }
+
+        let raw = self
+            .raw
+            .get(name)
+            .cloned()
+            .unwrap_or_else(|| Self::default_value(name));
+        let value = self.eval(&parse_color_expr(&raw))?;
+
+        self.visiting.remove(name);
+        self.resolved.insert(name, value.clone());
+        Ok(value)
+    }
+
+    fn eval(&mut self, expr: &ColorExpr) -> Result<String, ThemeError> {
+        match expr {
+            ColorExpr::Literal(value) => Ok(value.clone()),
+            ColorExpr::Reference(name) => {
+                let name = FIELD_NAMES
+                    .iter()
+                    .find(|f| **f == name)
+                    .copied()
+                    .ok_or_else(|| ThemeError::UnknownReference(name.clone()))?;
+                self.resolve(name)
+            }
+            ColorExpr::Lighten(inner, percent) => self.eval(inner).map(|hex| lighten(&hex, *percent)),
+            ColorExpr::Darken(inner, percent) => self.eval(inner).map(|hex| darken(&hex, *percent)),
+            ColorExpr::Alpha(inner, alpha) => self.eval(inner).map(|hex| with_alpha(&hex, *alpha)),
+        }
+    }
+
+    fn resolve_all(mut self) -> Result<Theme, ThemeError> {
+        Ok(Theme {
+            background_color: self.resolve("background_color")?,
+            primary_color: self.resolve("primary_color")?,
+            secondary_color: self.resolve("secondary_color")?,
+            tertiary_color: self.resolve("tertiary_color")?,
+            accent_color: self.resolve("accent_color")?,
+            text_primary: self.resolve("text_primary")?,
+            text_secondary: self.resolve("text_secondary")?,
+            shadow_color: self.resolve("shadow_color")?,
+        })
+    }
+}
+
+fn resolve_patch_tokens(patch: &ThemePatch) -> Theme {
+    let mut base = Theme::default();
+    base.apply_patch(patch);
+
+    let mut theme = resolve_theme_tokens(&base);
+
+    // A practice that customizes only the background shouldn't silently
+    // inherit text colors tuned for a different background and end up
+    // unreadable; fill in a contrast-aware pair instead.
+    if patch.background_color.is_some() {
+        let (auto_primary, auto_secondary) = contrast_aware_text_colors(&theme.background_color);
+        if patch.text_primary.is_none() {
+            theme.text_primary = auto_primary;
+        }
+        if patch.text_secondary.is_none() {
+            theme.text_secondary = auto_secondary;
+        }
+    }
+
+    theme
+}
+
+/// Resolves a fully-specified `Theme` whose fields may themselves be
+/// `$reference`/`lighten`/`darken`/`alpha` expressions rather than literal
+/// hex values, e.g. one loaded straight from a practice's `Colors`.
+fn resolve_theme_tokens(theme: &Theme) -> Theme {
+    let raw = HashMap::from([
+        ("background_color", theme.background_color.clone()),
+        ("primary_color", theme.primary_color.clone()),
+        ("secondary_color", theme.secondary_color.clone()),
+        ("tertiary_color", theme.tertiary_color.clone()),
+        ("accent_color", theme.accent_color.clone()),
+        ("text_primary", theme.text_primary.clone()),
+        ("text_secondary", theme.text_secondary.clone()),
+        ("shadow_color", theme.shadow_color.clone()),
+    ]);
+
+    TokenResolver::new(raw)
+        .resolve_all()
+        .unwrap_or_else(|_| Theme::default())
+}
+
+/// Named themes loaded from user theme files (desktop) or registered
+/// directly from JS (wasm), keyed by theme name so `set_theme` can select
+/// them ahead of resolving a practice's own palette.
+static THEME_REGISTRY: Lazy<Mutex<HashMap<String, Theme>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+pub fn register_theme(name: &str, theme: Theme) {
+    THEME_REGISTRY.lock().unwrap().insert(name.to_string(), theme);
+}
+
+/// Names of all themes currently registered, for a selector UI to enumerate.
+pub fn list_available_themes() -> Vec<String> {
+    let mut names: Vec<String> = THEME_REGISTRY.lock().unwrap().keys().cloned().collect();
+    names.sort();
+    names
+}
+
+/// Deserializes a single theme file (`.json` or `.ron`) into a `Theme`.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn load_theme_from_file(path: &std::path::Path) -> Result<Theme, ThemeError> {
+    let contents = std::fs::read_to_string(path).map_err(|e| ThemeError::Io(e.to_string()))?;
+
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("ron") => ron::from_str(&contents).map_err(|e| ThemeError::Parse(e.to_string())),
+        _ => serde_json::from_str(&contents).map_err(|e| ThemeError::Parse(e.to_string())),
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn user_themes_dir() -> Option<std::path::PathBuf> {
+    let home = std::env::var("HOME").ok()?;
+    Some(std::path::PathBuf::from(home).join(".config/inbe/themes"))
+}
+
+/// Scans `~/.config/inbe/themes/*.{json,ron}` and registers each file as a
+/// named theme (file stem becomes the name), so users can add palettes
+/// without rebuilding. Unreadable or malformed files are skipped, not fatal.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn load_user_themes() {
+    let Some(dir) = user_themes_dir() else {
+        return;
+    };
+    let Ok(entries) = std::fs::read_dir(&dir) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let is_theme_file = matches!(
+            path.extension().and_then(|ext| ext.to_str()),
+            Some("json") | Some("ron")
+        );
+        if !is_theme_file {
+            continue;
+        }
+        let Some(name) = path.file_stem().and_then(|s| s.to_str()) else {
+            continue;
+        };
+
+        match load_theme_from_file(&path) {
+            Ok(theme) => register_theme(name, theme),
+            Err(err) => println!("Skipping invalid theme file {}: {:?}", path.display(), err),
+        }
+    }
+}
+
+/// Registers a theme parsed from JSON by the JS side (e.g. read out of
+/// `localStorage`), since wasm has no filesystem to scan.
+#[cfg(target_arch = "wasm32")]
+pub fn register_theme_from_json(name: &str, json: &str) -> Result<(), ThemeError> {
+    let theme: Theme = serde_json::from_str(json).map_err(|e| ThemeError::Parse(e.to_string()))?;
+    register_theme(name, theme);
+    Ok(())
+}
+
 pub static CURRENT_THEME: Lazy<Mutex<Theme>> = Lazy::new(|| Mutex::new(Theme::default()));
 
+static CURRENT_VARIANTS: Lazy<Mutex<ThemeVariants>> =
+    Lazy::new(|| Mutex::new(ThemeVariants::single(Theme::default())));
+
+static MODE_OVERRIDE: Lazy<Mutex<Option<Mode>>> = Lazy::new(|| Mutex::new(None));
+
+/// Resolves a theme by, in order: a registered theme name (user themes
+/// loaded from disk or from JS), then a practice id, then the default.
 pub fn set_theme(practice_id: &str) {
-    let mut theme = CURRENT_THEME.lock().unwrap();
-    if let Some(practice) = get_practice_by_id(practice_id) {
-        *theme = Theme {
-            background_color: practice.visual.colors.background_color,
-            primary_color: practice.visual.colors.primary_color,
-            secondary_color: practice.visual.colors.secondary_color,
-            tertiary_color: practice.visual.colors.tertiary_color,
-            accent_color: practice.visual.colors.accent_color,
-            text_primary: practice.visual.colors.text_primary,
-            text_secondary: practice.visual.colors.text_secondary,
-            shadow_color: practice.visual.colors.shadow_color,
+    let registered = THEME_REGISTRY.lock().unwrap().get(practice_id).cloned();
+    let practice = get_practice_by_id(practice_id);
+
+    let variants = match (registered, practice) {
+        (Some(theme), Some(_)) => {
+            warn_theme_shadows_practice(practice_id);
+            ThemeVariants::single(theme)
+        }
+        (Some(theme), None) => ThemeVariants::single(theme),
+        (None, Some(practice)) => resolve_variants(&practice.visual),
+        (None, None) => ThemeVariants::single(Theme::default()),
+    };
+
+    *CURRENT_VARIANTS.lock().unwrap() = variants;
+    refresh_theme();
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn warn_theme_shadows_practice(id: &str) {
+    println!(
+        "Warning: registered theme '{}' shadows a practice with the same id",
+        id
+    );
+}
+
+#[cfg(target_arch = "wasm32")]
+fn warn_theme_shadows_practice(id: &str) {
+    web_sys::console::warn_1(
+        &format!(
+            "Warning: registered theme '{}' shadows a practice with the same id",
+            id
+        )
+        .into(),
+    );
+}
+
+fn resolve_variants(visual: &crate::models::practice::Visual) -> ThemeVariants {
+    if let Some(theme_set) = &visual.theme {
+        return ThemeVariants {
+            mode: theme_set.mode,
+            light: resolve_theme_tokens(&theme_set.light),
+            dark: resolve_theme_tokens(&theme_set.dark),
         };
-    } else {
-        *theme = Theme::default();
     }
-    
-    // Apply the theme
-    apply_theme(&theme);
+    if let Some(patch) = &visual.theme_patch {
+        return ThemeVariants::single(resolve_patch_tokens(patch));
+    }
+    ThemeVariants::single(resolve_theme_tokens(&visual.colors.clone().into()))
+}
+
+/// Overrides the active practice's preferred mode (e.g. a user toggling
+/// light/dark manually in a settings UI) and re-resolves immediately.
+pub fn set_appearance_mode(mode: Mode) {
+    *MODE_OVERRIDE.lock().unwrap() = Some(mode);
+    refresh_theme();
+}
+
+fn effective_mode() -> Mode {
+    MODE_OVERRIDE
+        .lock()
+        .unwrap()
+        .unwrap_or_else(|| CURRENT_VARIANTS.lock().unwrap().mode)
+}
+
+fn refresh_theme() {
+    let mode = effective_mode();
+    let theme = CURRENT_VARIANTS.lock().unwrap().resolve(mode).clone();
+    *CURRENT_THEME.lock().unwrap() = theme;
+    apply_theme(&get_current_theme());
 }
 
 pub fn get_current_theme() -> Theme {
@@ -82,6 +722,23 @@ pub fn get_theme_css() -> String {
     )
 }
 
+#[cfg(target_arch = "wasm32")]
+fn system_prefers_dark() -> bool {
+    use web_sys::window;
+
+    window()
+        .and_then(|w| w.match_media("(prefers-color-scheme: dark)").ok().flatten())
+        .map(|mql| mql.matches())
+        .unwrap_or(true)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn system_prefers_dark() -> bool {
+    // No OS appearance signal available outside the browser; default to the
+    // dark palette, matching this crate's historical default theme.
+    true
+}
+
 #[cfg(target_arch = "wasm32")]
 fn apply_theme(theme: &Theme) {
     use wasm_bindgen::prelude::*;
@@ -105,6 +762,40 @@ fn apply_theme(theme: &Theme) {
             }
         }
     }
+
+    ensure_system_listener();
+}
+
+/// Registers a `prefers-color-scheme: dark` listener exactly once so that,
+/// while the active mode is `System`, the `:root` variables are refreshed
+/// live as the OS appearance changes.
+#[cfg(target_arch = "wasm32")]
+fn ensure_system_listener() {
+    use wasm_bindgen::prelude::*;
+    use wasm_bindgen::JsCast;
+    use web_sys::window;
+
+    static LISTENER_REGISTERED: Lazy<Mutex<bool>> = Lazy::new(|| Mutex::new(false));
+
+    let mut registered = LISTENER_REGISTERED.lock().unwrap();
+    if *registered {
+        return;
+    }
+
+    let mql = match window().and_then(|w| w.match_media("(prefers-color-scheme: dark)").ok().flatten()) {
+        Some(mql) => mql,
+        None => return,
+    };
+
+    let callback = Closure::<dyn FnMut()>::new(|| {
+        if effective_mode() == Mode::System {
+            refresh_theme();
+        }
+    });
+    mql.set_onchange(Some(callback.as_ref().unchecked_ref()));
+    callback.forget();
+
+    *registered = true;
 }
 
 #[cfg(not(target_arch = "wasm32"))]
@@ -123,7 +814,7 @@ fn apply_theme(theme: &Theme) {
 #[cfg(test)]
 mod tests {
     use super::*;
-    
+
     #[test]
     fn test_set_theme() {
         // This test assumes that get_practice_by_id is mocked or a test practice is available
@@ -140,4 +831,150 @@ mod tests {
         assert_eq!(default_theme.primary_color, "#004d4d");
         assert_eq!(default_theme.secondary_color, "#006666");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_mode_override_takes_precedence() {
+        set_theme("whm_basic");
+        set_appearance_mode(Mode::Dark);
+        assert_eq!(effective_mode(), Mode::Dark);
+    }
+
+    #[test]
+    fn test_apply_patch_only_overwrites_present_fields() {
+        let mut theme = Theme::default();
+        let patch = ThemePatch {
+            accent_color: Some("#ff0000".to_string()),
+            background_color: Some("#111111".to_string()),
+            ..Default::default()
+        };
+        theme.apply_patch(&patch);
+
+        assert_eq!(theme.accent_color, "#ff0000");
+        assert_eq!(theme.background_color, "#111111");
+        assert_eq!(theme.primary_color, Theme::default().primary_color);
+    }
+
+    #[test]
+    fn test_resolve_patch_tokens_follows_references_and_derivations() {
+        let patch = ThemePatch {
+            primary_color: Some("#336699".to_string()),
+            secondary_color: Some("$primary_color".to_string()),
+            accent_color: Some("lighten($primary_color, 20%)".to_string()),
+            shadow_color: Some("alpha($primary_color, 0.3)".to_string()),
+            ..Default::default()
+        };
+        let theme = resolve_patch_tokens(&patch);
+
+        assert_eq!(theme.secondary_color, "#336699");
+        assert_ne!(theme.accent_color, "#336699");
+        assert_eq!(theme.shadow_color, "rgba(51, 102, 153, 0.3)");
+    }
+
+    #[test]
+    fn test_resolve_patch_tokens_supports_nested_derivations() {
+        let patch = ThemePatch {
+            primary_color: Some("#336699".to_string()),
+            // `alpha` as the outer call, wrapping a hex-producing `lighten`,
+            // is the supported nesting direction (see split_fn_args' doc
+            // comment) — `lighten`/`darken` can't take `alpha`'s rgba output.
+            accent_color: Some("alpha(lighten($primary_color, 10%), 0.3)".to_string()),
+            ..Default::default()
+        };
+        let theme = resolve_patch_tokens(&patch);
+
+        // Would silently mis-parse as "lighten($primary_color" / "10%), 0.3"
+        // without paren-aware splitting, producing a garbage color instead.
+        let expected = with_alpha(&lighten("#336699", 10.0), 0.3);
+        assert_eq!(theme.accent_color, expected);
+        assert!(expected.starts_with("rgba("));
+    }
+
+    #[test]
+    fn test_lighten_rejects_non_hex_input_instead_of_misparsing() {
+        let rgba = with_alpha("#336699", 0.5);
+        // lighten/darken can't operate on an rgba(...) string; they must
+        // pass it through unchanged rather than silently producing an
+        // unrelated gray (hex_to_rgb defaulting missing digits to "00").
+        assert_eq!(lighten(&rgba, 10.0), rgba);
+        assert_eq!(darken(&rgba, 10.0), rgba);
+    }
+
+    #[test]
+    fn test_unknown_reference_is_not_reported_as_cyclic() {
+        let patch = ThemePatch {
+            accent_color: Some("$does_not_exist".to_string()),
+            ..Default::default()
+        };
+        let mut raw = HashMap::new();
+        raw.insert("accent_color", "$does_not_exist".to_string());
+        let err = TokenResolver::new(raw).resolve_all().unwrap_err();
+
+        assert!(matches!(err, ThemeError::UnknownReference(_)));
+
+        // The patch path still degrades gracefully to the default.
+        let theme = resolve_patch_tokens(&patch);
+        assert_eq!(theme.accent_color, Theme::default().accent_color);
+    }
+
+    #[test]
+    fn test_resolve_patch_tokens_falls_back_to_default_on_cycle() {
+        let patch = ThemePatch {
+            primary_color: Some("$accent_color".to_string()),
+            accent_color: Some("$primary_color".to_string()),
+            ..Default::default()
+        };
+        let theme = resolve_patch_tokens(&patch);
+
+        assert_eq!(theme.primary_color, Theme::default().primary_color);
+        assert_eq!(theme.accent_color, Theme::default().accent_color);
+    }
+
+    #[test]
+    fn test_registered_theme_is_selected_by_name() {
+        let mut custom = Theme::default();
+        custom.accent_color = "#abcdef".to_string();
+        register_theme("midnight", custom);
+
+        assert!(list_available_themes().contains(&"midnight".to_string()));
+
+        set_theme("midnight");
+        assert_eq!(get_current_theme().accent_color, "#abcdef");
+    }
+
+    #[test]
+    fn test_warn_theme_shadows_practice_does_not_panic() {
+        warn_theme_shadows_practice("midnight");
+    }
+
+    #[test]
+    fn test_background_only_patch_picks_readable_text_colors() {
+        let patch = ThemePatch {
+            background_color: Some("#FFFFFF".to_string()),
+            ..Default::default()
+        };
+        let theme = resolve_patch_tokens(&patch);
+
+        assert_eq!(theme.text_primary, READABLE_DARK);
+
+        // text_secondary should be a muted variant of the chosen primary,
+        // not the rejected (illegible-on-white) extreme.
+        assert_ne!(theme.text_secondary, READABLE_LIGHT);
+        assert_ne!(theme.text_secondary, theme.text_primary);
+
+        let bg_luminance = relative_luminance(&theme.background_color);
+        let secondary_ratio = contrast_ratio(bg_luminance, relative_luminance(&theme.text_secondary));
+        assert!(secondary_ratio > WCAG_AA_CONTRAST);
+    }
+
+    #[test]
+    fn test_explicit_text_color_is_not_overridden() {
+        let patch = ThemePatch {
+            background_color: Some("#FFFFFF".to_string()),
+            text_primary: Some("#123456".to_string()),
+            ..Default::default()
+        };
+        let theme = resolve_patch_tokens(&patch);
+
+        assert_eq!(theme.text_primary, "#123456");
+    }
+}